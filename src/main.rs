@@ -1,12 +1,17 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufWriter;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use error_iter::ErrorIter as _;
 use log::error;
 use pixels::{Pixels, SurfaceTexture};
 use winit::{
     application::ApplicationHandler,
+    dpi::PhysicalPosition,
     error::EventLoopError,
-    event::WindowEvent,
+    event::{ElementState, KeyEvent, MouseButton, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     keyboard::{KeyCode, PhysicalKey},
     window::{Window, WindowAttributes},
@@ -16,6 +21,36 @@ const WIDTH: u32 = 320;
 const HEIGHT: u32 = 240;
 const BOX_SIZE: i16 = 64;
 
+/// Fixed simulation step: update the world 60 times per second.
+const STEP: Duration = Duration::from_nanos(1_000_000_000 / 60);
+/// Upper bound on the elapsed time fed into the accumulator per redraw, so a
+/// frozen or minimized window does not trigger a "spiral of death" catch-up.
+const MAX_ELAPSED: Duration = Duration::from_millis(250);
+
+/// Where recorded animations are written. The GIF delay unit is hundredths of
+/// a second, so a `3` delay at a ~33 ms capture interval plays back at the
+/// intended ~30 frames/sec regardless of how fast the redraw loop spins.
+const RECORDING_PATH: &str = "recording.gif";
+const RECORDING_DELAY: u16 = 3;
+const RECORDING_INTERVAL: Duration = Duration::from_millis(33);
+
+/// A simulation the engine loop can drive. Implementors supply the fixed pixel
+/// buffer size plus the update/draw hooks; all of the window, surface, input
+/// and error-handling plumbing lives in [`App`].
+trait Game {
+    const WIDTH: u32;
+    const HEIGHT: u32;
+
+    /// Create the initial game state.
+    fn new() -> Self;
+
+    /// Advance the simulation by one fixed timestep.
+    fn update(&mut self, input: &Input);
+
+    /// Draw the current state into the RGBA frame buffer.
+    fn draw(&self, frame: &mut [u8]);
+}
+
 struct World {
     box_x: i16,
     box_y: i16,
@@ -23,24 +58,208 @@ struct World {
     velocity_y: i16,
 }
 
-pub struct App {
+struct App<G: Game> {
     window: Option<Arc<Window>>,
-    pixels: Option<Pixels>,
-    world: World,
+    pixels: Option<Pixels<'static>>,
+    game: G,
+    last_instant: Instant,
+    accumulator: Duration,
+    recorder: Option<Recorder>,
+    input: Input,
+}
+
+/// Accumulates keyboard and mouse events between redraws and exposes a small
+/// polling API. The "just pressed"/"just released" sets are valid for a single
+/// frame and must be cleared with [`Input::clear_frame`] at the end of each
+/// `RedrawRequested`.
+#[derive(Default)]
+struct Input {
+    keys_held: HashSet<KeyCode>,
+    keys_pressed: HashSet<KeyCode>,
+    keys_released: HashSet<KeyCode>,
+    buttons_held: HashSet<MouseButton>,
+    buttons_pressed: HashSet<MouseButton>,
+    buttons_released: HashSet<MouseButton>,
+    cursor: Option<(f32, f32)>,
+    mouse_pixel: Option<(u32, u32)>,
+}
+
+impl Input {
+    /// Fold a `WindowEvent::KeyboardInput` into the key sets.
+    fn on_keyboard(&mut self, event: &KeyEvent) {
+        let PhysicalKey::Code(code) = event.physical_key else {
+            return;
+        };
+        match event.state {
+            ElementState::Pressed => {
+                // `insert` returns `false` for keys held across auto-repeat, so
+                // only the first press counts as "just pressed".
+                if !event.repeat && self.keys_held.insert(code) {
+                    self.keys_pressed.insert(code);
+                }
+            }
+            ElementState::Released => {
+                if self.keys_held.remove(&code) {
+                    self.keys_released.insert(code);
+                }
+            }
+        }
+    }
+
+    /// Fold a `WindowEvent::MouseInput` into the button sets.
+    fn on_mouse_button(&mut self, state: ElementState, button: MouseButton) {
+        match state {
+            ElementState::Pressed => {
+                if self.buttons_held.insert(button) {
+                    self.buttons_pressed.insert(button);
+                }
+            }
+            ElementState::Released => {
+                if self.buttons_held.remove(&button) {
+                    self.buttons_released.insert(button);
+                }
+            }
+        }
+    }
+
+    /// Record the latest cursor position in physical window coordinates.
+    fn on_cursor_moved(&mut self, position: PhysicalPosition<f64>) {
+        self.cursor = Some((position.x as f32, position.y as f32));
+    }
+
+    /// Whether `key` was pressed during the current frame.
+    #[allow(dead_code)] // part of the reusable polling API
+    fn key_pressed(&self, key: KeyCode) -> bool {
+        self.keys_pressed.contains(&key)
+    }
+
+    /// Whether `key` is currently held down.
+    fn key_held(&self, key: KeyCode) -> bool {
+        self.keys_held.contains(&key)
+    }
+
+    /// Whether `key` was released during the current frame.
+    #[allow(dead_code)] // part of the reusable polling API
+    fn key_released(&self, key: KeyCode) -> bool {
+        self.keys_released.contains(&key)
+    }
+
+    /// Whether `button` was pressed during the current frame.
+    #[allow(dead_code)] // part of the reusable polling API
+    fn mouse_pressed(&self, button: MouseButton) -> bool {
+        self.buttons_pressed.contains(&button)
+    }
+
+    /// Whether `button` is currently held down.
+    fn mouse_held(&self, button: MouseButton) -> bool {
+        self.buttons_held.contains(&button)
+    }
+
+    /// Whether `button` was released during the current frame.
+    #[allow(dead_code)] // part of the reusable polling API
+    fn mouse_released(&self, button: MouseButton) -> bool {
+        self.buttons_released.contains(&button)
+    }
+
+    /// Refresh the cached framebuffer-space cursor position by mapping the
+    /// last known window position through `pixels`. Called by [`App`] each
+    /// redraw so that [`Input::mouse_pixel`] is available to `Game` code,
+    /// which never sees the surface directly.
+    fn refresh_mouse_pixel(&mut self, pixels: &Pixels) {
+        self.mouse_pixel = self.cursor.and_then(|(x, y)| {
+            pixels
+                .window_pos_to_pixel((x, y))
+                .ok()
+                .map(|(px, py)| (px as u32, py as u32))
+        });
+    }
+
+    /// The cursor position in framebuffer coordinates, or `None` when it is
+    /// outside the pixel buffer.
+    fn mouse_pixel(&self) -> Option<(u32, u32)> {
+        self.mouse_pixel
+    }
+
+    /// Clear the per-frame "just pressed/released" sets; call at the end of
+    /// each `RedrawRequested`.
+    fn clear_frame(&mut self) {
+        self.keys_pressed.clear();
+        self.keys_released.clear();
+        self.buttons_pressed.clear();
+        self.buttons_released.clear();
+    }
+}
+
+/// Captures the pixel buffer to an animated GIF while active, at most one frame
+/// per redraw and no more often than the capture interval. Quantization to the
+/// 256-color GIF palette is done per frame with the `gif` crate's built-in
+/// NeuQuant quantizer.
+struct Recorder {
+    encoder: gif::Encoder<BufWriter<File>>,
+    width: u16,
+    height: u16,
+    interval: Duration,
+    accumulator: Duration,
+}
+
+impl Recorder {
+    /// Start a new recording at `path`, sized to the `width` × `height` pixel
+    /// buffer it will capture.
+    fn new(path: &str, width: u32, height: u32) -> Result<Self, gif::EncodingError> {
+        let (width, height) = (width as u16, height as u16);
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = gif::Encoder::new(file, width, height, &[])?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        Ok(Self {
+            encoder,
+            width,
+            height,
+            interval: RECORDING_INTERVAL,
+            accumulator: Duration::ZERO,
+        })
+    }
+
+    /// Advance the capture clock by `elapsed` and, once at least one target
+    /// interval has passed, quantize and append a single RGBA frame. This
+    /// decouples the GIF frame rate from the (uncapped) redraw rate.
+    fn record(&mut self, frame: &[u8], elapsed: Duration) -> Result<(), gif::EncodingError> {
+        self.accumulator += elapsed;
+        if self.accumulator < self.interval {
+            return Ok(());
+        }
+        // Capture at most one frame per redraw; drop any further backlog so a
+        // slow encode can't snowball.
+        self.accumulator = Duration::ZERO;
+
+        let mut rgba = frame.to_vec();
+        let mut gif_frame = gif::Frame::from_rgba_speed(self.width, self.height, &mut rgba, 10);
+        gif_frame.delay = RECORDING_DELAY;
+        self.encoder.write_frame(&gif_frame)
+    }
 }
 
 fn main() -> Result<(), EventLoopError> {
     let event_loop = EventLoop::new().unwrap();
     event_loop.set_control_flow(ControlFlow::Poll);
-    let mut app = App {
-        window: None,
-        pixels: None,
-        world: World::new(),
-    };
+    let mut app = App::<World>::new();
     event_loop.run_app(&mut app)
 }
 
-impl ApplicationHandler for App {
+impl<G: Game> App<G> {
+    fn new() -> Self {
+        Self {
+            window: None,
+            pixels: None,
+            game: G::new(),
+            last_instant: Instant::now(),
+            accumulator: Duration::ZERO,
+            recorder: None,
+            input: Input::default(),
+        }
+    }
+}
+
+impl<G: Game> ApplicationHandler for App<G> {
     fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
         let window = event_loop
             .create_window(WindowAttributes::default())
@@ -50,9 +269,11 @@ impl ApplicationHandler for App {
 
         self.pixels = {
             let (window_width, window_height) = window.inner_size().into();
-            let surface_texture = SurfaceTexture::new(window_width, window_height, &window);
-            match Pixels::new(WIDTH, HEIGHT, surface_texture) {
+            let surface_texture =
+                SurfaceTexture::new(window_width, window_height, window.clone());
+            match Pixels::new(G::WIDTH, G::HEIGHT, surface_texture) {
                 Ok(pixels) => {
+                    self.last_instant = Instant::now();
                     window.request_redraw();
                     Some(pixels)
                 }
@@ -73,16 +294,37 @@ impl ApplicationHandler for App {
     ) {
         match event {
             WindowEvent::CloseRequested => {
+                // Dropping the recorder flushes the encoder and writes the
+                // GIF trailer.
+                self.recorder = None;
                 event_loop.exit();
             }
             WindowEvent::RedrawRequested => {
-                self.world.update();
-                self.world.draw(self.pixels.as_mut().unwrap().frame_mut());
+                let now = Instant::now();
+                let elapsed = (now - self.last_instant).min(MAX_ELAPSED);
+                self.last_instant = now;
+                self.accumulator += elapsed;
+                if let Some(pixels) = self.pixels.as_ref() {
+                    self.input.refresh_mouse_pixel(pixels);
+                }
+                while self.accumulator >= STEP {
+                    self.game.update(&self.input);
+                    self.accumulator -= STEP;
+                }
+                let pixels = self.pixels.as_mut().unwrap();
+                self.game.draw(pixels.frame_mut());
+                if let Some(recorder) = self.recorder.as_mut() {
+                    if let Err(err) = recorder.record(pixels.frame(), elapsed) {
+                        log_error("recorder.record", err);
+                        self.recorder = None;
+                    }
+                }
                 if let Err(err) = self.pixels.as_ref().unwrap().render() {
                     log_error("pixels.render", err);
                     event_loop.exit();
                 }
                 self.window.as_ref().unwrap().request_redraw();
+                self.input.clear_frame();
             }
             WindowEvent::Resized(size) => {
                 if let Err(err) = self
@@ -95,21 +337,133 @@ impl ApplicationHandler for App {
                     event_loop.exit()
                 }
             }
-            WindowEvent::KeyboardInput {
-                device_id,
-                event,
-                is_synthetic,
-            } => {
+            WindowEvent::ScaleFactorChanged { .. } => {
+                let (new_width, new_height) =
+                    self.window.as_ref().unwrap().inner_size().into();
+                if let Err(err) = self
+                    .pixels
+                    .as_mut()
+                    .unwrap()
+                    .resize_surface(new_width, new_height)
+                {
+                    log_error("pixels.resize_surface", err);
+                    event_loop.exit()
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.input.on_cursor_moved(position);
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                self.input.on_mouse_button(state, button);
+            }
+            WindowEvent::KeyboardInput { event, .. } => {
+                self.input.on_keyboard(&event);
                 if let PhysicalKey::Code(KeyCode::Escape) = event.physical_key {
                     event_loop.exit();
                 }
+                if let PhysicalKey::Code(KeyCode::KeyR) = event.physical_key {
+                    if event.state == ElementState::Pressed && !event.repeat {
+                        if self.recorder.is_some() {
+                            // Drop to finalize the current recording.
+                            self.recorder = None;
+                        } else {
+                            match Recorder::new(RECORDING_PATH, G::WIDTH, G::HEIGHT) {
+                                Ok(recorder) => self.recorder = Some(recorder),
+                                Err(err) => log_error("Recorder::new", err),
+                            }
+                        }
+                    }
+                }
             }
             _ => {}
         }
     }
 }
 
-impl World {
+/// A thin drawing surface over an RGBA (`Rgba8UnormSrgb`) pixel buffer.
+///
+/// Coordinates are in framebuffer pixels and may be negative; writes that fall
+/// outside the buffer are silently clipped.
+struct Canvas<'a> {
+    frame: &'a mut [u8],
+    width: u32,
+    height: u32,
+}
+
+impl<'a> Canvas<'a> {
+    /// Wrap a frame buffer of `width` × `height` RGBA pixels.
+    fn new(frame: &'a mut [u8], width: u32, height: u32) -> Self {
+        Self {
+            frame,
+            width,
+            height,
+        }
+    }
+
+    /// Fill the whole buffer with a single color.
+    fn clear(&mut self, rgba: [u8; 4]) {
+        for pixel in self.frame.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&rgba);
+        }
+    }
+
+    /// Set a single pixel, ignoring coordinates outside the buffer.
+    fn set_pixel(&mut self, x: i32, y: i32, rgba: [u8; 4]) {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return;
+        }
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        self.frame[i..i + 4].copy_from_slice(&rgba);
+    }
+
+    /// Fill an axis-aligned rectangle.
+    fn fill_rect(&mut self, x: i32, y: i32, w: u32, h: u32, rgba: [u8; 4]) {
+        for dy in 0..h as i32 {
+            for dx in 0..w as i32 {
+                self.set_pixel(x + dx, y + dy, rgba);
+            }
+        }
+    }
+
+    /// Draw a line with integer Bresenham stepping, driving along whichever
+    /// axis spans the larger absolute delta.
+    fn draw_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, rgba: [u8; 4]) {
+        let dx = (x1 - x0).abs();
+        let dy = (y1 - y0).abs();
+        let sx = if x0 < x1 { 1 } else { -1 };
+        let sy = if y0 < y1 { 1 } else { -1 };
+        let (mut x, mut y) = (x0, y0);
+
+        if dx >= dy {
+            let mut err = 2 * dy - dx;
+            for _ in 0..=dx {
+                self.set_pixel(x, y, rgba);
+                if err > 0 {
+                    y += sy;
+                    err -= 2 * dx;
+                }
+                err += 2 * dy;
+                x += sx;
+            }
+        } else {
+            let mut err = 2 * dx - dy;
+            for _ in 0..=dy {
+                self.set_pixel(x, y, rgba);
+                if err > 0 {
+                    x += sx;
+                    err -= 2 * dy;
+                }
+                err += 2 * dx;
+                y += sy;
+            }
+        }
+    }
+}
+
+impl Game for World {
+    const WIDTH: u32 = WIDTH;
+    const HEIGHT: u32 = HEIGHT;
+
     /// Create a new `World` instance that can draw a moving box.
     fn new() -> Self {
         Self {
@@ -121,7 +475,32 @@ impl World {
     }
 
     /// Update the `World` internal state; bounce the box around the screen.
-    fn update(&mut self) {
+    ///
+    /// Holding the arrow keys nudges the box, and holding the left mouse
+    /// button snaps it to the cursor — a small demonstration of the input
+    /// helper feeding a `Game`.
+    fn update(&mut self, input: &Input) {
+        if input.mouse_held(MouseButton::Left) {
+            if let Some((mx, my)) = input.mouse_pixel() {
+                self.box_x = mx as i16 - BOX_SIZE / 2;
+                self.box_y = my as i16 - BOX_SIZE / 2;
+                return;
+            }
+        }
+
+        if input.key_held(KeyCode::ArrowLeft) {
+            self.velocity_x = -1;
+        }
+        if input.key_held(KeyCode::ArrowRight) {
+            self.velocity_x = 1;
+        }
+        if input.key_held(KeyCode::ArrowUp) {
+            self.velocity_y = -1;
+        }
+        if input.key_held(KeyCode::ArrowDown) {
+            self.velocity_y = 1;
+        }
+
         if self.box_x <= 0 || self.box_x + BOX_SIZE > WIDTH as i16 {
             self.velocity_x *= -1;
         }
@@ -137,23 +516,21 @@ impl World {
     ///
     /// Assumes the default texture format: `wgpu::TextureFormat::Rgba8UnormSrgb`
     fn draw(&self, frame: &mut [u8]) {
-        for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
-            let x = (i % WIDTH as usize) as i16;
-            let y = (i / WIDTH as usize) as i16;
-
-            let inside_the_box = x >= self.box_x
-                && x < self.box_x + BOX_SIZE
-                && y >= self.box_y
-                && y < self.box_y + BOX_SIZE;
+        let mut canvas = Canvas::new(frame, WIDTH, HEIGHT);
+        canvas.clear([0x48, 0xb2, 0xe8, 0xff]);
+        canvas.fill_rect(
+            self.box_x as i32,
+            self.box_y as i32,
+            BOX_SIZE as u32,
+            BOX_SIZE as u32,
+            [0x5e, 0x48, 0xe8, 0xff],
+        );
 
-            let rgba = if inside_the_box {
-                [0x5e, 0x48, 0xe8, 0xff]
-            } else {
-                [0x48, 0xb2, 0xe8, 0xff]
-            };
-
-            pixel.copy_from_slice(&rgba);
-        }
+        // Cross the box with its diagonals to show off the line primitive.
+        let (x0, y0) = (self.box_x as i32, self.box_y as i32);
+        let (x1, y1) = (x0 + BOX_SIZE as i32 - 1, y0 + BOX_SIZE as i32 - 1);
+        canvas.draw_line(x0, y0, x1, y1, [0xff, 0xff, 0xff, 0xff]);
+        canvas.draw_line(x0, y1, x1, y0, [0xff, 0xff, 0xff, 0xff]);
     }
 }
 